@@ -1,5 +1,6 @@
 //! Task Module that handles cooperative OS functionality and preemptive multitasking
 #![no_std]
+#![feature(naked_functions)]
 extern crate alloc;
 
 use alloc::boxed::Box;
@@ -13,6 +14,9 @@ use core::{
 pub mod executor;
 pub mod keyboard;
 pub mod mouse;
+pub mod preempt;
+
+use x86_64::VirtAddr;
 
 /// Task ID struct that enforces unique ID's to be handed out to various tasks
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
@@ -27,9 +31,18 @@ impl TaskId {
 }
 
 /// Task struct that holds a unique ID and a future
+///
+/// A task is either a cooperative async task (polled by the [`executor`]) or a
+/// preemptible kernel/user thread scheduled off the timer interrupt. Preemptible
+/// threads carry a saved register [`Context`](preempt::Context) and a kernel stack;
+/// cooperative async tasks leave both `None`.
 pub struct Task {
     id: TaskId,
     future: Pin<Box<dyn Future<Output = ()>>>,
+    /// Saved register frame for a preemptible thread, `None` for async tasks
+    context: Option<preempt::Context>,
+    /// Top of this thread's kernel stack, `None` for async tasks
+    kernel_stack: Option<VirtAddr>,
 }
 
 impl Task {
@@ -38,6 +51,8 @@ impl Task {
         Task {
             id: TaskId::new(),
             future: Box::pin(future),
+            context: None,
+            kernel_stack: None,
         }
     }
     /// Poll this task to see if it is ready or is still pending
@@ -0,0 +1,301 @@
+//! Timer-driven preemptive scheduler.
+//!
+//! The [`executor`](crate::executor) polls async tasks cooperatively — a task only
+//! yields at an `await` point. This module adds the preemptive half the module doc
+//! comment promises: kernel/user threads that are interrupted by the timer, have
+//! their registers saved onto their own kernel stack, and are resumed later in
+//! round-robin order. Both kinds of task share the one [`TaskId`](crate::TaskId)
+//! allocator.
+//!
+//! [`timer_preempt`] is the interrupt entry that [`install`] puts on a timer vector: it
+//! builds a [`Context`] on the interrupted thread's stack, hands its stack pointer to
+//! [`Scheduler::tick`], swaps `CR3` if the chosen thread lives in another address
+//! space, and `iretq`s onto that thread.
+
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, VecDeque};
+use spin::Mutex;
+
+use x86_64::instructions::interrupts::without_interrupts;
+use x86_64::instructions::port::Port;
+use x86_64::instructions::segmentation::{Segment, CS, SS};
+use x86_64::registers::control::{Cr3, Cr3Flags};
+use x86_64::structures::idt::InterruptDescriptorTable;
+use x86_64::structures::paging::{PhysFrame, Size4KiB};
+use x86_64::VirtAddr;
+
+use crate::executor::Executor;
+use crate::{Task, TaskId};
+
+/// Saved register frame for a preempted thread.
+///
+/// [`timer_preempt`] pushes this onto the thread's kernel stack; the scheduler
+/// remembers the stack pointer it lives at and the interrupt epilogue pops it back
+/// and `iretq`s to resume the thread exactly where it was interrupted. The field
+/// order is load-bearing — it is the exact layout the push/pop sequence produces.
+///
+/// In 64-bit mode the CPU's own interrupt frame is always five qwords (`rip`, `cs`,
+/// `rflags`, `rsp`, `ss`), whether or not the privilege level changes, and `iretq`
+/// always pops all five, so the struct ends with the whole frame.
+#[derive(Debug, Clone, Copy, Default)]
+#[repr(C)]
+pub struct Context {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rbp: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rdx: u64,
+    pub rcx: u64,
+    pub rbx: u64,
+    pub rax: u64,
+    /// Instruction pointer to resume at (bottom of the interrupt frame)
+    pub rip: u64,
+    pub cs: u64,
+    pub rflags: u64,
+    /// Stack pointer to resume with (top of the interrupt frame)
+    pub rsp: u64,
+    pub ss: u64,
+}
+
+/// A preemptible thread tracked by the [`Scheduler`].
+struct Thread {
+    /// The owning [`Task`]; its `context`/`kernel_stack` fields carry the thread's
+    /// last saved register frame and the stack it was handed.
+    task: Task,
+    /// Kernel-stack pointer at which the thread's saved [`Context`] currently lives
+    stack_pointer: VirtAddr,
+    /// Address space to switch to (`CR3`), or `None` for a kernel thread that runs
+    /// in the kernel address space
+    cr3: Option<PhysFrame<Size4KiB>>,
+}
+
+/// Round-robin scheduler over the set of runnable preemptible threads.
+pub struct Scheduler {
+    threads: BTreeMap<TaskId, Thread>,
+    run_queue: VecDeque<TaskId>,
+    current: Option<TaskId>,
+}
+
+/// The outcome of a timer tick: which stack pointer to resume on and, if the new
+/// thread lives in a different address space, the `CR3` to load first.
+pub struct Switch {
+    pub stack_pointer: VirtAddr,
+    pub cr3: Option<PhysFrame<Size4KiB>>,
+}
+
+/// The global preemptive scheduler.
+pub static SCHEDULER: Mutex<Scheduler> = Mutex::new(Scheduler::new());
+
+impl Scheduler {
+    /// Create an empty scheduler.
+    pub const fn new() -> Self {
+        Scheduler {
+            threads: BTreeMap::new(),
+            run_queue: VecDeque::new(),
+            current: None,
+        }
+    }
+
+    /// Advance the scheduler one timer tick.
+    ///
+    /// `saved` is the stack pointer at which the interrupted thread's registers were
+    /// just pushed. The current thread is rotated to the back of the run queue and
+    /// the next runnable thread is chosen; the returned [`Switch`] tells the caller
+    /// where to resume and whether a `CR3` swap is needed. Returns `None` when there
+    /// is no *other* runnable thread, so the current one simply continues.
+    pub fn tick(&mut self, saved: VirtAddr) -> Option<Switch> {
+        // `run_queue` holds the runnable threads *other* than `current`. If it is
+        // empty there is nothing to switch to and the current thread keeps running.
+        if self.run_queue.is_empty() {
+            return None;
+        }
+
+        // The first time we preempt, `saved` is the live context of the cooperative
+        // executor / boot thread, which has never been registered. Capture it as a
+        // thread so it stays in the rotation instead of being abandoned the moment a
+        // spawned thread is scheduled.
+        let current = match self.current {
+            Some(current) => current,
+            None => self.bootstrap(),
+        };
+        if let Some(thread) = self.threads.get_mut(&current) {
+            thread.stack_pointer = saved;
+            // Keep the Task's own context mirror up to date with the live frame.
+            thread.task.context = Some(unsafe { *saved.as_ptr::<Context>() });
+        }
+        self.run_queue.push_back(current);
+
+        let next = self.run_queue.pop_front()?;
+        self.current = Some(next);
+        let thread = self.threads.get(&next)?;
+        Some(Switch {
+            stack_pointer: thread.stack_pointer,
+            cr3: thread.cr3,
+        })
+    }
+
+    /// Register a prepared thread and queue it to run.
+    fn add(&mut self, task: Task, stack_pointer: VirtAddr, cr3: Option<PhysFrame<Size4KiB>>) {
+        let id = task.id;
+        self.threads.insert(id, Thread { task, stack_pointer, cr3 });
+        self.run_queue.push_back(id);
+    }
+
+    /// Synthesize a thread entry for the already-running boot / executor context.
+    ///
+    /// Its stack pointer is filled in by the caller from the interrupt's `saved`
+    /// value; the placeholder future never completes, so the executor keeps running
+    /// whenever this thread is scheduled back in.
+    fn bootstrap(&mut self) -> TaskId {
+        let task = Task::new(core::future::pending());
+        let id = task.id;
+        self.threads.insert(
+            id,
+            Thread {
+                task,
+                stack_pointer: VirtAddr::zero(),
+                cr3: None,
+            },
+        );
+        id
+    }
+}
+
+impl Executor {
+    /// Spawn a preemptible kernel thread that begins executing `entry`.
+    ///
+    /// A fresh kernel stack is allocated and seeded with an initial [`Context`] so
+    /// that the first timer tick that selects this thread returns onto `entry` with
+    /// a clean stack. Returns the thread's [`TaskId`], drawn from the same allocator
+    /// as cooperative async tasks.
+    ///
+    /// The whole body runs with interrupts disabled: it takes `stack::alloc_stack`'s
+    /// lock and then `SCHEDULER`'s, and a timer tick landing while `SCHEDULER` is held
+    /// would have `preempt_tick` try to re-enter that lock from inside the ISR and
+    /// spin forever (the interrupted thread can never run again to release it).
+    pub fn spawn_thread(&mut self, entry: fn() -> !) -> TaskId {
+        without_interrupts(|| {
+            let stack_top = stack::alloc_stack(2);
+
+            // Seed the context the scheduler restores first, placing it at the top of
+            // the new kernel stack so the iretq frame is exactly where the epilogue
+            // expects.
+            let frame_addr = stack_top.as_u64() - core::mem::size_of::<Context>() as u64;
+            let context = Context {
+                rip: entry as usize as u64,
+                // Same-privilege selector so the resuming `iretq` does not #GP.
+                cs: CS::get_reg().0 as u64,
+                rflags: 0x202, // reserved bit 1 set, interrupts enabled
+                // `iretq` pops these too; the thread starts on an empty stack.
+                rsp: stack_top.as_u64(),
+                ss: SS::get_reg().0 as u64,
+                ..Context::default()
+            };
+            unsafe { (frame_addr as *mut Context).write(context) };
+
+            let mut task = Task::new(core::future::pending());
+            let id = task.id;
+            task.context = Some(context);
+            task.kernel_stack = Some(stack_top);
+
+            SCHEDULER
+                .lock()
+                .add(task, VirtAddr::new(frame_addr), None);
+            id
+        })
+    }
+}
+
+/// Install [`timer_preempt`] as the handler for `vector` in `idt`.
+///
+/// Calling this with the timer vector makes every timer IRQ drive
+/// [`Scheduler::tick`]. The kernel's own interrupt initialization does not call it,
+/// so the timer vector keeps the handler installed there. The handler address is installed
+/// raw because [`timer_preempt`] is a naked function that owns the whole interrupt
+/// frame itself rather than the `extern "x86-interrupt"` ABI.
+pub fn install(idt: &mut InterruptDescriptorTable, vector: u8) {
+    unsafe {
+        idt[vector as usize].set_handler_addr(VirtAddr::new(timer_preempt as u64));
+    }
+}
+
+/// The scheduling decision made on a timer tick, called from [`timer_preempt`].
+///
+/// Returns the stack pointer to resume on; when the chosen thread lives in another
+/// address space its `CR3` is loaded here first. The timer IRQ is acknowledged to
+/// the PIC before returning so further ticks keep arriving.
+extern "C" fn preempt_tick(saved: u64) -> u64 {
+    let switch = SCHEDULER.lock().tick(VirtAddr::new(saved));
+
+    // End-of-interrupt to the master PIC for the timer (IRQ0).
+    unsafe { Port::<u8>::new(0x20).write(0x20u8) };
+
+    match switch {
+        Some(switch) => {
+            if let Some(frame) = switch.cr3 {
+                let (current, _) = Cr3::read();
+                if current != frame {
+                    unsafe { Cr3::write(frame, Cr3Flags::empty()) };
+                }
+            }
+            switch.stack_pointer.as_u64()
+        }
+        None => saved,
+    }
+}
+
+/// Timer interrupt entry for preemptive scheduling.
+///
+/// Install this as the handler for the timer vector in the IDT. It saves the
+/// interrupted thread's general-purpose registers (building a [`Context`] on its
+/// stack), asks [`preempt_tick`] which stack to resume on, then restores that
+/// thread's registers and `iretq`s onto it.
+#[naked]
+pub unsafe extern "C" fn timer_preempt() {
+    core::arch::asm!(
+        // Build the Context: rax first (adjacent to the CPU frame), r15 last.
+        "push rax",
+        "push rbx",
+        "push rcx",
+        "push rdx",
+        "push rsi",
+        "push rdi",
+        "push rbp",
+        "push r8",
+        "push r9",
+        "push r10",
+        "push r11",
+        "push r12",
+        "push r13",
+        "push r14",
+        "push r15",
+        "mov rdi, rsp",   // &Context for preempt_tick
+        "call {tick}",
+        "mov rsp, rax",   // resume on the chosen thread's saved frame
+        "pop r15",
+        "pop r14",
+        "pop r13",
+        "pop r12",
+        "pop r11",
+        "pop r10",
+        "pop r9",
+        "pop r8",
+        "pop rbp",
+        "pop rdi",
+        "pop rsi",
+        "pop rdx",
+        "pop rcx",
+        "pop rbx",
+        "pop rax",
+        "iretq",
+        tick = sym preempt_tick,
+        options(noreturn),
+    );
+}
@@ -0,0 +1,143 @@
+//! A fixed-size block allocator used as the kernel's [`#[global_allocator]`].
+//!
+//! The kernel and its async tasks make a great many small `Box`/`Vec` allocations;
+//! servicing each of those through the general linked-list allocator is the
+//! bottleneck. Instead we keep a free list per block-size class: an allocation is
+//! rounded up to the smallest class that fits and popped from that list in O(1),
+//! falling back to a linked-list heap only when a class's free list is empty or for
+//! requests larger than the biggest class.
+
+use alloc::alloc::{GlobalAlloc, Layout};
+use core::ptr::{self, NonNull};
+
+use linked_list_allocator::Heap;
+use spin::{Mutex, MutexGuard};
+
+/// The global allocator the kernel allocates through.
+///
+/// [`init_heap`](super::init_heap) hands it the mapped heap region once paging is up.
+#[global_allocator]
+pub static ALLOCATOR: Locked<FixedSizeBlockAllocator> =
+    Locked::new(FixedSizeBlockAllocator::new());
+
+/// A spin-mutex newtype so we can implement the foreign `GlobalAlloc` trait on a
+/// local type (`Mutex` itself is foreign).
+pub struct Locked<A> {
+    inner: Mutex<A>,
+}
+
+impl<A> Locked<A> {
+    /// Wrap `inner` in a new lock.
+    pub const fn new(inner: A) -> Self {
+        Locked {
+            inner: Mutex::new(inner),
+        }
+    }
+
+    /// Acquire the lock, spinning until it is free.
+    pub fn lock(&self) -> MutexGuard<A> {
+        self.inner.lock()
+    }
+}
+
+/// The block sizes to use, in bytes.
+///
+/// Each size must be a power of two because it doubles as the allocation's
+/// alignment when a fresh block is carved from the fallback allocator.
+const BLOCK_SIZES: &[usize] = &[8, 16, 32, 64, 128, 256, 512, 1024, 2048];
+
+/// Node in the intrusive free list; it lives inside the free block itself.
+struct ListNode {
+    next: Option<&'static mut ListNode>,
+}
+
+/// Choose the index of the smallest block size class that fits `layout`.
+///
+/// Returns `None` for allocations larger (or more strictly aligned) than the
+/// biggest class, which are then handed to the fallback allocator.
+fn list_index(layout: &Layout) -> Option<usize> {
+    let required_block_size = layout.size().max(layout.align());
+    BLOCK_SIZES.iter().position(|&s| s >= required_block_size)
+}
+
+/// Fixed-size block allocator backed by a linked-list fallback heap.
+pub struct FixedSizeBlockAllocator {
+    list_heads: [Option<&'static mut ListNode>; BLOCK_SIZES.len()],
+    fallback_allocator: Heap,
+}
+
+impl FixedSizeBlockAllocator {
+    /// Create an empty allocator; call [`init`](Self::init) before use.
+    pub const fn new() -> Self {
+        const EMPTY: Option<&'static mut ListNode> = None;
+        FixedSizeBlockAllocator {
+            list_heads: [EMPTY; BLOCK_SIZES.len()],
+            fallback_allocator: Heap::empty(),
+        }
+    }
+
+    /// Initialize the fallback allocator with the given heap bounds.
+    ///
+    /// # Safety
+    /// The caller must guarantee that `[heap_start, heap_start + heap_size)` is
+    /// unused and stays valid, and that this is only called once.
+    pub unsafe fn init(&mut self, heap_start: usize, heap_size: usize) {
+        self.fallback_allocator.init(heap_start, heap_size);
+    }
+
+    /// Allocate from the fallback linked-list allocator.
+    fn fallback_alloc(&mut self, layout: Layout) -> *mut u8 {
+        match self.fallback_allocator.allocate_first_fit(layout) {
+            Ok(ptr) => ptr.as_ptr(),
+            Err(_) => ptr::null_mut(),
+        }
+    }
+}
+
+unsafe impl GlobalAlloc for Locked<FixedSizeBlockAllocator> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let mut allocator = self.lock();
+        match list_index(&layout) {
+            Some(index) => match allocator.list_heads[index].take() {
+                // Pop a free block of this class off its list.
+                Some(node) => {
+                    allocator.list_heads[index] = node.next.take();
+                    node as *mut ListNode as *mut u8
+                }
+                // No block cached: carve a fresh one of the class's size from the
+                // fallback. Block size doubles as alignment, so this is valid to
+                // later hand back to the same list.
+                None => {
+                    let block_size = BLOCK_SIZES[index];
+                    let block_align = block_size;
+                    let layout = Layout::from_size_align(block_size, block_align).unwrap();
+                    allocator.fallback_alloc(layout)
+                }
+            },
+            None => allocator.fallback_alloc(layout),
+        }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let mut allocator = self.lock();
+        match list_index(&layout) {
+            // Push the freed block back onto its list by writing the next-pointer
+            // into the block's own memory.
+            Some(index) => {
+                let new_node = ListNode {
+                    next: allocator.list_heads[index].take(),
+                };
+                // The block is at least as large and as aligned as a `ListNode`.
+                assert!(core::mem::size_of::<ListNode>() <= BLOCK_SIZES[index]);
+                assert!(core::mem::align_of::<ListNode>() <= BLOCK_SIZES[index]);
+                let new_node_ptr = ptr as *mut ListNode;
+                new_node_ptr.write(new_node);
+                allocator.list_heads[index] = Some(&mut *new_node_ptr);
+            }
+            None => {
+                let ptr = NonNull::new(ptr).unwrap();
+                allocator.fallback_allocator.deallocate(ptr, layout);
+            }
+        }
+    }
+}
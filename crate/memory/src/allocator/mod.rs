@@ -0,0 +1,40 @@
+//! Kernel heap: maps the heap region and drives the global allocator.
+
+pub mod fixed_size_block;
+
+use x86_64::structures::paging::mapper::MapToError;
+use x86_64::structures::paging::{Page, PageTableFlags, Size4KiB};
+use x86_64::VirtAddr;
+
+use crate::phys::map_next;
+
+/// Virtual address the kernel heap starts at.
+pub const HEAP_START: usize = 0xFFFF_F000_0000_0000;
+/// Size of the kernel heap in bytes.
+pub const HEAP_SIZE: usize = 100 * 1024;
+
+/// Map the heap region and hand it to the global allocator.
+///
+/// Every page in `[HEAP_START, HEAP_START + HEAP_SIZE)` is backed by a fresh frame
+/// pulled through [`map_next`], then the [`fixed_size_block::ALLOCATOR`]'s fallback
+/// heap is initialized over that range so allocations can begin.
+pub fn init_heap() -> Result<(), MapToError<Size4KiB>> {
+    let page_range = {
+        let heap_start = VirtAddr::new(HEAP_START as u64);
+        let heap_end = heap_start + HEAP_SIZE - 1u64;
+        let heap_start_page = Page::<Size4KiB>::containing_address(heap_start);
+        let heap_end_page = Page::<Size4KiB>::containing_address(heap_end);
+        Page::range_inclusive(heap_start_page, heap_end_page)
+    };
+
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+    for page in page_range {
+        map_next(page, flags)?.flush();
+    }
+
+    unsafe {
+        fixed_size_block::ALLOCATOR.lock().init(HEAP_START, HEAP_SIZE);
+    }
+
+    Ok(())
+}
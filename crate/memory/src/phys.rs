@@ -1,8 +1,9 @@
 //! Physical Frame structures and functionality
 
 use bootloader::boot_info::{MemoryRegion, MemoryRegionKind, MemoryRegions};
+use printer::println;
 use spin::{Mutex, Once};
-use x86_64::structures::paging::mapper::MapToError;
+use x86_64::structures::paging::mapper::{MapToError, MapperFlush};
 use x86_64::{PhysAddr, VirtAddr};
 
 /// A global frame allocator initialized from [init](PhysFrameAllocator::init)
@@ -64,6 +65,34 @@ impl PhysFrameAllocator {
         });
 
         BYTES_AVAILABLE_RAM.call_once(|| usable_memory_region.end - usable_memory_region.start);
+
+        report_memory_regions(memory_regions);
+    }
+
+    /// Number of frames the usable region is divided into.
+    fn total_frames(&self) -> u64 {
+        (self.usable_memory_region.end - self.usable_memory_region.start) >> 12
+    }
+
+    /// Live accounting of the usable region derived from the bitmap: how much RAM it
+    /// covers and how many of its frames are currently allocated vs. free.
+    pub fn stats(&self) -> MemoryStats {
+        let total_frames = self.total_frames();
+
+        let mut used_frames = 0;
+        for index in 0..total_frames {
+            let word_ptr = (BITMAP_START as u64 + ((index >> 6) << 3)) as *mut u64;
+            let word = unsafe { *word_ptr };
+            if word & (1u64 << (index % 64)) != 0 {
+                used_frames += 1;
+            }
+        }
+
+        MemoryStats {
+            total_ram: self.usable_memory_region.end - self.usable_memory_region.start,
+            used_frames,
+            free_frames: total_frames - used_frames,
+        }
     }
 
     /// Get the physical frame from the bitmap index
@@ -72,6 +101,121 @@ impl PhysFrameAllocator {
         PhysFrame::<Size4KiB>::containing_address(frame_addr)
     }
 
+    /// Set (`used = true`) or clear the bit for a single frame index in the bitmap
+    unsafe fn set_bit(&self, index: u64, used: bool) {
+        let word_ptr = (BITMAP_START as u64 + ((index >> 6) << 3)) as *mut u64;
+        let mask = 1u64 << (index % 64);
+        if used {
+            *word_ptr |= mask;
+        } else {
+            *word_ptr &= !mask;
+        }
+    }
+
+    /// Allocate `count` physically contiguous frames.
+    ///
+    /// The bitmap is scanned word by word, carrying the length of the current run of
+    /// zero bits across `u64` boundaries rather than resetting at each word, so a run
+    /// that straddles two words is still found. Each word is read from the bitmap
+    /// once and then walked entirely in registers: `trailing_zeros` gives the length
+    /// of the next zero-run directly, so a free or fully-allocated word is consumed
+    /// in one step instead of testing 64 individual bits. On success every bit in
+    /// `[start, start + count)` is marked used and the range of frames is returned;
+    /// `None` means no run of the requested length exists.
+    pub fn allocate_contiguous(&mut self, count: usize) -> Option<PhysFrameRange> {
+        if count == 0 {
+            return None;
+        }
+        let count = count as u64;
+        // Bound the scan to the frames that actually back usable RAM; the bitmap is
+        // rounded up to a whole number of frames, so its trailing padding bits are
+        // zero and would otherwise match a run past `usable_memory_region.end`.
+        let total_frames = self.total_frames();
+
+        let mut run = 0u64;
+        let mut bit = 0u64;
+        while bit < total_frames && run < count {
+            let word_ptr = (BITMAP_START as u64 + ((bit >> 6) << 3)) as *mut u64;
+            let mut word = unsafe { *word_ptr };
+
+            // Beyond `total_frames` the bitmap is zero padding, not real free
+            // frames; mask it off as "used" so a run can't be found crossing into it.
+            let remaining = total_frames - bit;
+            if remaining < 64 {
+                word |= u64::MAX << remaining;
+            }
+
+            if word == u64::MAX {
+                run = 0;
+                bit += 64;
+                continue;
+            }
+            if word == 0 {
+                run += 64;
+                bit += 64;
+                continue;
+            }
+
+            // `word` mixes free and used bits. Walk its zero-runs via
+            // `trailing_zeros` on the cached value instead of re-reading the bitmap
+            // one bit at a time.
+            let mut consumed = 0u64;
+            loop {
+                let free = word.trailing_zeros() as u64;
+                if run + free >= count {
+                    let start = bit + consumed - run;
+                    for i in start..start + count {
+                        unsafe { self.set_bit(i, true) };
+                    }
+                    return Some(PhysFrameRange {
+                        start: self.frame_from_bit_index(start),
+                        end: self.frame_from_bit_index(start + count),
+                    });
+                }
+
+                // The free run is capped by the `1` bit right after it; skip past
+                // both and keep walking the rest of this word.
+                run = 0;
+                consumed += free + 1;
+                if consumed >= 64 {
+                    break;
+                }
+                word >>= free + 1;
+                if word == 0 {
+                    run = 64 - consumed;
+                    break;
+                }
+            }
+            bit += 64;
+        }
+        // The scan also stops once a run reaching the end of the last word read (all
+        // free words, or the free tail after a word's last used bit) is long enough.
+        if run < count {
+            return None;
+        }
+        let start = bit - run;
+        for i in start..start + count {
+            unsafe { self.set_bit(i, true) };
+        }
+        Some(PhysFrameRange {
+            start: self.frame_from_bit_index(start),
+            end: self.frame_from_bit_index(start + count),
+        })
+    }
+
+    /// Free a range of frames previously handed out by [`allocate_contiguous`] by
+    /// clearing their bits in the bitmap.
+    ///
+    /// # Safety
+    /// The caller must ensure the range is no longer in use.
+    pub unsafe fn deallocate_contiguous(&mut self, range: PhysFrameRange) {
+        let start = (range.start.start_address().as_u64() - self.usable_memory_region.start) >> 12;
+        let end = (range.end.start_address().as_u64() - self.usable_memory_region.start) >> 12;
+        for index in start..end {
+            self.set_bit(index, false);
+        }
+    }
+
     /// Allocate a specific frame from an addr alligned to the nearest 4KiB frame, will return none if
     /// frame is in use
     pub fn allocate_frame_nth(&self, start: PhysAddr) -> Option<PhysFrame> {
@@ -98,6 +242,7 @@ impl PhysFrameAllocator {
 }
 
 // TODO: CONSIDER TURNING BITMAP HANDLING ROUTINES INTO A STRUCT
+use x86_64::structures::paging::frame::PhysFrameRange;
 use x86_64::structures::paging::{
     FrameAllocator, FrameDeallocator, Mapper, Page, PageSize, PageTableFlags, PhysFrame, Size4KiB,
 };
@@ -123,17 +268,17 @@ unsafe impl FrameAllocator<Size4KiB> for PhysFrameAllocator {
                     if quadword & 1 == 0 {
                         // Usable PhysFrame
                         unsafe { *bm_ptr = qw_clone | (0x01 << index) };
+                        // Bit index = (word number) * 64 + bit within the word
+                        let word = (bm_ptr as u64 - BITMAP_START as u64) / 8;
                         // Return the frame containing the physical address of the bit in the bitmap
-                        return Some(
-                            self.frame_from_bit_index(bm_ptr as u64 - BITMAP_START as u64 + index),
-                        );
+                        return Some(self.frame_from_bit_index(word * 64 + index));
                     }
                     index += 1;
                     quadword >>= 1;
                 }
             }
-            // Point to next quadword in the map
-            unsafe { bm_ptr = bm_ptr.add(8) };
+            // Point to next quadword in the map (a `*mut u64` advances one word per `add`)
+            unsafe { bm_ptr = bm_ptr.add(1) };
         }
         None
     }
@@ -163,6 +308,60 @@ impl FrameDeallocator<Size4KiB> for PhysFrameAllocator {
     }
 }
 
+/// A snapshot of usable-memory accounting, returned by [`memory_stats`].
+#[derive(Debug, Clone, Copy)]
+pub struct MemoryStats {
+    /// Bytes of usable RAM managed by the frame allocator
+    pub total_ram: u64,
+    /// Frames currently handed out
+    pub used_frames: u64,
+    /// Frames still available
+    pub free_frames: u64,
+}
+
+/// Live memory accounting for the usable region, for subsystems that want to check
+/// pressure before a large allocation or surface a `meminfo`-style shell command.
+pub fn memory_stats() -> MemoryStats {
+    FRAME_ALLOCATOR.wait().unwrap().inner.lock().stats()
+}
+
+/// Print a region-by-region memory map at boot and accumulate per-kind totals.
+///
+/// Each region is logged as `[start-end] kind`; usable, bootloader and reserved
+/// bytes are summed so the boot log shows where the firmware's RAM went.
+fn report_memory_regions(memory_regions: &'static MemoryRegions) {
+    let mut usable = 0u64;
+    let mut bootloader = 0u64;
+    let mut reserved = 0u64;
+
+    println!("memory map:");
+    for region in memory_regions.iter() {
+        let size = region.end - region.start;
+        println!("  [{:#018x}-{:#018x}] {:?}", region.start, region.end, region.kind);
+        match region.kind {
+            MemoryRegionKind::Usable => usable += size,
+            MemoryRegionKind::Bootloader => bootloader += size,
+            _ => reserved += size,
+        }
+    }
+
+    println!(
+        "memory totals: usable {} KiB, bootloader {} KiB, reserved {} KiB",
+        usable >> 10,
+        bootloader >> 10,
+        reserved >> 10
+    );
+
+    // Currently-allocated vs. free frames, derived from the freshly-built bitmap.
+    let stats = memory_stats();
+    println!(
+        "frame usage: {} used, {} free ({} KiB managed)",
+        stats.used_frames,
+        stats.free_frames,
+        stats.total_ram >> 10
+    );
+}
+
 /// This function is called during the initialization of the frame allocator to identity map the bit map frames
 fn map_bit_frames(
     bit_map_region: MemoryRegion,
@@ -211,6 +410,44 @@ fn map_bit_frames(
     Ok(())
 }
 
+/// Map `page` to `frame` with `flags` in the kernel page table.
+///
+/// A thin convenience wrapper around locking [`KERNEL_PAGE_TABLE`] and calling
+/// `map_to` with the global [`FRAME_ALLOCATOR`], so callers that just want a single
+/// mapping do not have to repeat the lock / allocator / flush dance by hand. The
+/// returned [`MapperFlush`] is left for the caller to `flush` once the mapping is live.
+pub fn map(
+    page: Page<Size4KiB>,
+    frame: PhysFrame<Size4KiB>,
+    flags: PageTableFlags,
+) -> Result<MapperFlush<Size4KiB>, MapToError<Size4KiB>> {
+    let mut frame_allocator = FRAME_ALLOCATOR.wait().unwrap();
+    unsafe {
+        KERNEL_PAGE_TABLE
+            .wait()
+            .unwrap()
+            .lock()
+            .map_to(page, frame, flags, &mut frame_allocator)
+    }
+}
+
+/// Map `page` to the next free frame pulled from the global [`FRAME_ALLOCATOR`].
+///
+/// Returns [`MapToError::FrameAllocationFailed`] when no frames remain. This is the
+/// common case — the caller does not care which physical frame backs the page, only
+/// that one is mapped in.
+pub fn map_next(
+    page: Page<Size4KiB>,
+    flags: PageTableFlags,
+) -> Result<MapperFlush<Size4KiB>, MapToError<Size4KiB>> {
+    let frame = FRAME_ALLOCATOR
+        .wait()
+        .unwrap()
+        .allocate_frame()
+        .ok_or(MapToError::FrameAllocationFailed)?;
+    map(page, frame, flags)
+}
+
 /// Wrapper struct for implementing FrameAllocator traits around the mutex type
 pub struct PhysFrameAllocatorWrapper {
     pub inner: Mutex<PhysFrameAllocator>,
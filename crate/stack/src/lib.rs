@@ -2,8 +2,104 @@
 //! and functions thereof
 #![no_std]
 
-use x86_64::structures::paging::{Mapper, Page, RecursivePageTable, Size4KiB};
+extern crate alloc;
 
-pub fn alloc_stack(size_in_pages: usize, page_table : RecursivePageTable) {
-    
+use alloc::vec::Vec;
+use spin::Mutex;
+use x86_64::structures::paging::page::PageRangeInclusive;
+use x86_64::structures::paging::{
+    FrameDeallocator, Mapper, Page, PageSize, PageTableFlags, Size4KiB,
+};
+use x86_64::VirtAddr;
+
+use memory::phys::{map_next, FRAME_ALLOCATOR};
+use memory::KERNEL_PAGE_TABLE;
+
+/// Base of the dedicated kernel stack "land".
+///
+/// Task stacks are carved out of this region one after another. Each stack grows
+/// downward (x86 convention) and is separated from the stack below it by a single
+/// unmapped guard page, so that a stack overflow steps off the mapped range and
+/// triggers a page fault instead of silently clobbering its neighbour.
+pub const STACK_START: u64 = 0xFFFF_F000_0001_9000;
+
+/// A stack that has been handed out by [`alloc_stack`], remembered so the matching
+/// [`dealloc_stack`] can unmap the pages and free the backing frames.
+struct StackRange {
+    /// Top of the stack (highest address + one page), as returned to the caller
+    top: VirtAddr,
+    /// The mapped pages backing the stack, excluding the guard page below them
+    pages: PageRangeInclusive<Size4KiB>,
+}
+
+/// Cursor into the stack land pointing at the next free guard page, and the set of
+/// live stacks so they can be torn down again.
+static STACKS: Mutex<StackLand> = Mutex::new(StackLand {
+    next: STACK_START,
+    live: Vec::new(),
+});
+
+struct StackLand {
+    next: u64,
+    live: Vec<StackRange>,
+}
+
+/// Allocate a `size_in_pages` task stack from the kernel stack land.
+///
+/// Each page is backed by a fresh frame mapped through [`map_next`] — the shared
+/// kernel mapping path — with one unmapped guard page left immediately below the
+/// lowest mapped page to catch overflow. The returned [`VirtAddr`] is the top of the
+/// stack (highest mapped address + one page) because x86 stacks grow downward.
+pub fn alloc_stack(size_in_pages: usize) -> VirtAddr {
+    let mut land = STACKS.lock();
+
+    // The cursor points at the guard page; the first mapped page sits just above it.
+    let guard = land.next;
+    let bottom = guard + Size4KiB::SIZE;
+    let top = bottom + (size_in_pages as u64) * Size4KiB::SIZE;
+
+    let first_page = Page::<Size4KiB>::containing_address(VirtAddr::new(bottom));
+    let last_page = Page::<Size4KiB>::containing_address(VirtAddr::new(top - Size4KiB::SIZE));
+    let pages = Page::range_inclusive(first_page, last_page);
+
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+    for page in pages {
+        map_next(page, flags)
+            .expect("failed to map task stack page")
+            .flush();
+    }
+
+    // Advance the cursor past this stack, leaving the next guard page in place.
+    land.next = top;
+    land.live.push(StackRange {
+        top: VirtAddr::new(top),
+        pages,
+    });
+
+    VirtAddr::new(top)
+}
+
+/// Tear down a stack previously handed out by [`alloc_stack`].
+///
+/// The mapped pages are unmapped and their backing frames returned to the
+/// [`FRAME_ALLOCATOR`]. `top` must be the value [`alloc_stack`] returned.
+pub fn dealloc_stack(top: VirtAddr) {
+    let mut land = STACKS.lock();
+
+    let index = land
+        .live
+        .iter()
+        .position(|stack| stack.top == top)
+        .expect("dealloc_stack called with an unknown stack top");
+    let stack = land.live.remove(index);
+
+    let mut page_table = KERNEL_PAGE_TABLE.wait().unwrap().lock();
+    let mut frame_allocator = FRAME_ALLOCATOR.wait().unwrap();
+    for page in stack.pages {
+        let (frame, flush) = page_table
+            .unmap(page)
+            .expect("failed to unmap task stack page");
+        flush.flush();
+        unsafe { frame_allocator.deallocate_frame(frame) };
+    }
 }
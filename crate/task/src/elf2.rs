@@ -0,0 +1,284 @@
+//! User-space ELF64 loader.
+//!
+//! [`load_elf`] parses a statically-linked ELF64 image, builds a fresh address
+//! space for it (keeping the kernel's higher-half mappings so the kernel stays
+//! reachable after a `CR3` switch), maps every `PT_LOAD` segment at its requested
+//! virtual address and returns a [`LoadedElf`] handle carrying the new `CR3` frame
+//! and the entry point. No user stack is set up: `stack::alloc_stack` maps its pages
+//! in the kernel stack land without `USER_ACCESSIBLE`.
+//!
+//! This kernel uses *recursive* paging and has no linear physical-memory map, so the
+//! new address space is built by temporarily mapping each freshly-allocated frame
+//! (page tables and segment pages alike) into a scratch slot of the kernel address
+//! space through the shared [`map`](memory::phys::map) layer, editing it there, and
+//! unmapping it again.
+//!
+//! The scratch slot is a single global window, so the map/edit/unmap sequence must
+//! run as one atomic step: [`with_table`] and [`with_bytes`] disable interrupts for
+//! their whole body so a timer tick can't preempt the caller mid-edit and hand the
+//! window to another thread's `load_elf` call before it's unmapped.
+
+use xmas_elf::program::{self, ProgramHeader};
+use xmas_elf::ElfFile;
+
+use x86_64::instructions::interrupts::without_interrupts;
+use x86_64::structures::paging::{
+    FrameAllocator, Mapper, Page, PageSize, PageTable, PageTableFlags, PhysFrame, Size4KiB,
+};
+use x86_64::VirtAddr;
+
+use memory::active_level_4_table;
+use memory::phys::{map, FRAME_ALLOCATOR};
+use memory::KERNEL_PAGE_TABLE;
+
+/// Kernel virtual address used as a one-frame scratch window while editing the new
+/// address space's frames.
+///
+/// Layout of the `0xFFFF_F000_xxxx_xxxx` kernel window:
+///
+/// ```text
+///   0xFFFF_F000_0000_0000  kernel heap        (allocator::HEAP_SIZE bytes)
+///   0xFFFF_F000_0001_9000  stack land         (grows upward, guard page per stack)
+///   0xFFFF_F000_FFFF_F000  ELF-loader scratch (this page)
+/// ```
+///
+/// The scratch page sits at the very top of the window so it can never be overrun by
+/// the upward-growing stack land — the earlier `0xFFFF_F000_0002_0000` slot collided
+/// with the third spawned stack.
+const SCRATCH: u64 = 0xFFFF_F000_FFFF_F000;
+
+/// First PML4 index belonging to the kernel's higher half. Entries `[256, 512)`
+/// are copied into every new address space so the kernel remains mapped.
+const KERNEL_PML4_START: usize = 256;
+
+/// A loaded user program: the root page table backing its address space and the
+/// virtual address execution should begin at.
+pub struct LoadedElf {
+    /// Frame holding the new PML4, to be loaded into `CR3`.
+    pub cr3: PhysFrame<Size4KiB>,
+    /// Virtual entry point of the program.
+    pub entry_point: VirtAddr,
+}
+
+/// Map `frame` into the scratch window and return a pointer to it.
+fn scratch_map(frame: PhysFrame<Size4KiB>) -> *mut u8 {
+    let page = Page::<Size4KiB>::containing_address(VirtAddr::new(SCRATCH));
+    map(page, frame, PageTableFlags::PRESENT | PageTableFlags::WRITABLE)
+        .expect("failed to map scratch page")
+        .flush();
+    SCRATCH as *mut u8
+}
+
+/// Release the scratch window.
+fn scratch_unmap() {
+    let page = Page::<Size4KiB>::containing_address(VirtAddr::new(SCRATCH));
+    let (_frame, flush) = KERNEL_PAGE_TABLE
+        .wait()
+        .unwrap()
+        .lock()
+        .unmap(page)
+        .expect("failed to unmap scratch page");
+    flush.flush();
+}
+
+/// Edit a frame as a [`PageTable`] through the scratch window.
+///
+/// The map, edit and unmap run with interrupts disabled (see the module docs) so a
+/// preempting timer tick can never hand the scratch window to another thread while
+/// `f` still holds a pointer into it.
+fn with_table<R>(frame: PhysFrame<Size4KiB>, f: impl FnOnce(&mut PageTable) -> R) -> R {
+    without_interrupts(|| {
+        let table = unsafe { &mut *(scratch_map(frame) as *mut PageTable) };
+        let result = f(table);
+        scratch_unmap();
+        result
+    })
+}
+
+/// Edit a frame as raw bytes through the scratch window.
+///
+/// See [`with_table`]: the map/edit/unmap sequence runs with interrupts disabled so
+/// it can't be preempted mid-window.
+fn with_bytes<R>(frame: PhysFrame<Size4KiB>, f: impl FnOnce(&mut [u8]) -> R) -> R {
+    without_interrupts(|| {
+        let ptr = scratch_map(frame);
+        let bytes = unsafe { core::slice::from_raw_parts_mut(ptr, Size4KiB::SIZE as usize) };
+        let result = f(bytes);
+        scratch_unmap();
+        result
+    })
+}
+
+/// Return the child table at `index` of `parent`, allocating and zeroing a fresh one
+/// (and linking it into `parent`) if the entry is empty.
+fn ensure_child(
+    parent: PhysFrame<Size4KiB>,
+    index: usize,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> PhysFrame<Size4KiB> {
+    if let Some(child) = with_table(parent, |table| {
+        let entry = &table[index];
+        if entry.is_unused() {
+            None
+        } else {
+            Some(entry.frame().expect("unexpected huge page in ELF address space"))
+        }
+    }) {
+        return child;
+    }
+
+    let child = frame_allocator
+        .allocate_frame()
+        .expect("out of frames allocating page table");
+    with_table(child, |table| table.zero());
+    with_table(parent, |table| {
+        table[index].set_frame(
+            child,
+            PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE,
+        )
+    });
+    child
+}
+
+/// Map `vaddr` inside the address space rooted at `pml4`, returning its backing
+/// frame and whether that frame was freshly allocated.
+///
+/// Two `PT_LOAD` segments frequently share a page after page-rounding (a read-only
+/// segment ending, a read-write one beginning). If the page is already mapped its
+/// existing frame is reused and the new `flags` are merged in, so neither segment's
+/// bytes nor its access rights are lost: `WRITABLE` is added permissively, but
+/// `NO_EXECUTE` is only kept if *every* segment sharing the page is non-executable,
+/// so a page still holding the tail of an executable segment doesn't go NX just
+/// because the next segment doesn't need to execute.
+fn map_or_get(
+    pml4: PhysFrame<Size4KiB>,
+    vaddr: VirtAddr,
+    flags: PageTableFlags,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> (PhysFrame<Size4KiB>, bool) {
+    let p3 = ensure_child(pml4, usize::from(vaddr.p4_index()), frame_allocator);
+    let p2 = ensure_child(p3, usize::from(vaddr.p3_index()), frame_allocator);
+    let p1 = ensure_child(p2, usize::from(vaddr.p2_index()), frame_allocator);
+    let index = usize::from(vaddr.p1_index());
+    with_table(p1, |table| {
+        let entry = &mut table[index];
+        if entry.is_unused() {
+            let frame = frame_allocator
+                .allocate_frame()
+                .expect("out of frames mapping ELF segment");
+            entry.set_frame(frame, flags);
+            (frame, true)
+        } else {
+            let frame = entry
+                .frame()
+                .expect("unexpected huge page in ELF address space");
+            // `WRITABLE` adds permissively (either segment wanting to write makes the
+            // shared page writable), but `NO_EXECUTE` must not: a plain `|` would set
+            // NX on a page still holding the tail of an executable segment just
+            // because the following segment is non-executable. NX only belongs on
+            // the merged page if *every* segment sharing it asked for NX.
+            let mut merged = (entry.flags() | flags) & !PageTableFlags::NO_EXECUTE;
+            if entry.flags().contains(PageTableFlags::NO_EXECUTE)
+                && flags.contains(PageTableFlags::NO_EXECUTE)
+            {
+                merged |= PageTableFlags::NO_EXECUTE;
+            }
+            entry.set_frame(frame, merged);
+            (frame, false)
+        }
+    })
+}
+
+/// Translate the segment's ELF permission flags into page-table flags.
+///
+/// Every user segment is `USER_ACCESSIBLE | PRESENT`; `WRITABLE` tracks the write
+/// bit and `NO_EXECUTE` is set for any segment that is not executable.
+fn flags_for_segment(flags: program::Flags) -> PageTableFlags {
+    let mut page_flags = PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE;
+    if flags.is_write() {
+        page_flags |= PageTableFlags::WRITABLE;
+    }
+    if !flags.is_execute() {
+        page_flags |= PageTableFlags::NO_EXECUTE;
+    }
+    page_flags
+}
+
+/// Parse `image` and map it into a fresh address space.
+///
+/// # Panics
+/// Panics if the image is not a valid ELF64 or if the frame allocator is exhausted.
+pub fn load_elf(image: &[u8]) -> LoadedElf {
+    let elf = ElfFile::new(image).expect("invalid ELF image");
+
+    let mut frame_allocator = FRAME_ALLOCATOR.wait().unwrap();
+
+    // Allocate the new PML4, zero it, and copy the kernel's higher-half entries so
+    // the kernel stays mapped once we switch CR3.
+    let pml4_frame = frame_allocator
+        .allocate_frame()
+        .expect("out of frames allocating user PML4");
+    let kernel_p4 = unsafe { active_level_4_table() };
+    with_table(pml4_frame, |new_p4| {
+        new_p4.zero();
+        for index in KERNEL_PML4_START..512 {
+            new_p4[index] = kernel_p4[index].clone();
+        }
+    });
+
+    for header in elf.program_iter() {
+        if let Ok(program::Type::Load) = header.get_type() {
+            map_segment(&header, image, pml4_frame, &mut frame_allocator);
+        }
+    }
+
+    LoadedElf {
+        cr3: pml4_frame,
+        entry_point: VirtAddr::new(elf.header.pt2.entry_point()),
+    }
+}
+
+/// Map a single `PT_LOAD` segment, copying `p_filesz` bytes of file data and
+/// zero-filling the remaining `p_memsz - p_filesz` bytes (the BSS tail).
+fn map_segment(
+    header: &ProgramHeader,
+    image: &[u8],
+    pml4: PhysFrame<Size4KiB>,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) {
+    let flags = flags_for_segment(header.flags());
+    let virt_start = header.virtual_addr();
+    let mem_size = header.mem_size();
+    let file_size = header.file_size();
+    let file_offset = header.offset();
+
+    let start_page = Page::<Size4KiB>::containing_address(VirtAddr::new(virt_start));
+    let end_page = Page::<Size4KiB>::containing_address(VirtAddr::new(virt_start + mem_size - 1));
+
+    for page in Page::range_inclusive(start_page, end_page) {
+        let page_base = page.start_address().as_u64();
+        let (frame, newly) = map_or_get(pml4, page.start_address(), flags, frame_allocator);
+
+        with_bytes(frame, |bytes| {
+            // Only zero a freshly-allocated frame (so gaps and the BSS tail read as
+            // zero). A reused frame already holds an earlier segment's bytes in the
+            // shared page — re-zeroing it would drop them.
+            if newly {
+                for slot in bytes.iter_mut() {
+                    *slot = 0;
+                }
+            }
+
+            // Copy this segment's file-backed bytes that land in this page, indexing
+            // relative to the rounded-down page base so a non-page-aligned `p_vaddr`
+            // (where `page_base < virt_start`) cannot underflow.
+            let copy_start = page_base.max(virt_start);
+            let copy_end = (page_base + Size4KiB::SIZE).min(virt_start + file_size);
+            for va in copy_start..copy_end {
+                let in_page = (va - page_base) as usize;
+                let in_file = file_offset + (va - virt_start);
+                bytes[in_page] = image[in_file as usize];
+            }
+        });
+    }
+}
@@ -133,6 +133,98 @@ fn test_same_frame_alloc_dealloc() {
     }
 }
 
+#[test_case]
+fn test_contiguous_frame_alloc_dealloc() {
+    use memory::phys::FRAME_ALLOCATOR;
+
+    let wrapper = FRAME_ALLOCATOR.wait().unwrap();
+    let mut frame_allocator = wrapper.inner.lock();
+
+    let range = frame_allocator.allocate_contiguous(8).unwrap();
+    let frames: alloc::vec::Vec<_> = range.into_iter().collect();
+    assert_eq!(frames.len(), 8);
+    for window in frames.windows(2) {
+        assert_eq!(
+            window[1].start_address().as_u64() - window[0].start_address().as_u64(),
+            4096
+        );
+    }
+
+    unsafe { frame_allocator.deallocate_contiguous(range) };
+
+    let reused = frame_allocator.allocate_contiguous(8).unwrap();
+    assert_eq!(reused.start, frames[0]);
+}
+
+#[test_case]
+fn test_contiguous_frame_alloc_across_word_boundary() {
+    use memory::phys::FRAME_ALLOCATOR;
+    use x86_64::structures::paging::FrameAllocator;
+
+    let wrapper = FRAME_ALLOCATOR.wait().unwrap();
+    let mut frame_allocator = wrapper.inner.lock();
+
+    // Fragment the front of the bitmap with single-frame allocations so the run the
+    // contiguous request needs only exists once it has crossed a 64-bit word
+    // boundary, exercising the carried-run bookkeeping between words.
+    let mut singles = alloc::vec::Vec::new();
+    for _ in 0..70 {
+        singles.push(frame_allocator.allocate_frame().unwrap());
+    }
+
+    let range = frame_allocator.allocate_contiguous(100).unwrap();
+    let frames: alloc::vec::Vec<_> = range.into_iter().collect();
+    assert_eq!(frames.len(), 100);
+    for window in frames.windows(2) {
+        assert_eq!(
+            window[1].start_address().as_u64() - window[0].start_address().as_u64(),
+            4096
+        );
+    }
+
+    unsafe { frame_allocator.deallocate_contiguous(range) };
+}
+
+#[test_case]
+fn test_scheduler_round_robin() {
+    use coop::executor::Executor;
+    use coop::preempt::{Context, SCHEDULER};
+    use x86_64::VirtAddr;
+
+    fn idle_a() -> ! {
+        loop {}
+    }
+    fn idle_b() -> ! {
+        loop {}
+    }
+
+    let mut executor = Executor::new();
+    executor.spawn_thread(idle_a);
+    executor.spawn_thread(idle_b);
+
+    // `tick` only reads through this pointer to mirror the interrupted thread's
+    // context; its contents don't matter here, only that it's valid to dereference.
+    let mut dummy = Context::default();
+    let saved = VirtAddr::new(&mut dummy as *mut Context as u64);
+
+    // `tick` records `saved` as the interrupted thread's stack pointer, so each later
+    // tick is handed the frame the previous tick resumed, as a thread interrupted
+    // at the same stack depth would push it.
+    let first = SCHEDULER.lock().tick(saved).unwrap().stack_pointer;
+    let second = SCHEDULER.lock().tick(first).unwrap().stack_pointer;
+    let third = SCHEDULER.lock().tick(second).unwrap().stack_pointer;
+    let fourth = SCHEDULER.lock().tick(third).unwrap().stack_pointer;
+
+    // Three runnable threads are in rotation here: the two spawned above, plus the
+    // one `tick` bootstraps for the already-running caller on its first call. Every
+    // tick should hand back a different thread until the fourth, which wraps back
+    // around to the first.
+    assert_ne!(first, second);
+    assert_ne!(second, third);
+    assert_ne!(first, third);
+    assert_eq!(first, fourth);
+}
+
 #[test_case]
 fn test_new_frame_alloc() {
     use memory::phys::FRAME_ALLOCATOR;
@@ -142,6 +234,76 @@ fn test_new_frame_alloc() {
     assert_ne!(frame_allocator.allocate_frame(), frame_allocator.allocate_frame())
 }
 
+#[test_case]
+fn test_alloc_dealloc_stack() {
+    use stack::{alloc_stack, dealloc_stack};
+
+    let top = alloc_stack(2);
+    assert!(top.is_aligned(4096u64));
+
+    // The mapped pages should actually be writable.
+    let probe = (top.as_u64() - 8) as *mut u64;
+    unsafe {
+        probe.write(0xDEAD_BEEF);
+        assert_eq!(probe.read(), 0xDEAD_BEEF);
+    }
+
+    dealloc_stack(top);
+}
+
+#[test_case]
+fn test_load_elf_minimal() {
+    use alloc::vec::Vec;
+    use task::elf2::load_elf;
+
+    // Hand-build the smallest valid statically-linked ELF64 image `load_elf` can
+    // parse: a 64-byte header and a single `PT_LOAD` program header covering the
+    // whole file (headers included), with a few bytes of "text" after it.
+    fn elf64(vaddr: u64, p_flags: u32, body: &[u8]) -> Vec<u8> {
+        let mut image = Vec::new();
+        image.extend_from_slice(&[0x7f, b'E', b'L', b'F', 2, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        image.extend_from_slice(&2u16.to_le_bytes()); // e_type = ET_EXEC
+        image.extend_from_slice(&0x3eu16.to_le_bytes()); // e_machine = EM_X86_64
+        image.extend_from_slice(&1u32.to_le_bytes()); // e_version
+        image.extend_from_slice(&vaddr.to_le_bytes()); // e_entry
+        image.extend_from_slice(&64u64.to_le_bytes()); // e_phoff
+        image.extend_from_slice(&0u64.to_le_bytes()); // e_shoff
+        image.extend_from_slice(&0u32.to_le_bytes()); // e_flags
+        image.extend_from_slice(&64u16.to_le_bytes()); // e_ehsize
+        image.extend_from_slice(&56u16.to_le_bytes()); // e_phentsize
+        image.extend_from_slice(&1u16.to_le_bytes()); // e_phnum
+        image.extend_from_slice(&0u16.to_le_bytes()); // e_shentsize
+        image.extend_from_slice(&0u16.to_le_bytes()); // e_shnum
+        image.extend_from_slice(&0u16.to_le_bytes()); // e_shstrndx
+        assert_eq!(image.len(), 64);
+
+        let headers_len = 64 + 56;
+        let file_size = (headers_len + body.len()) as u64;
+        image.extend_from_slice(&1u32.to_le_bytes()); // p_type = PT_LOAD
+        image.extend_from_slice(&p_flags.to_le_bytes());
+        image.extend_from_slice(&0u64.to_le_bytes()); // p_offset
+        image.extend_from_slice(&vaddr.to_le_bytes()); // p_vaddr
+        image.extend_from_slice(&vaddr.to_le_bytes()); // p_paddr
+        image.extend_from_slice(&file_size.to_le_bytes()); // p_filesz
+        image.extend_from_slice(&file_size.to_le_bytes()); // p_memsz
+        image.extend_from_slice(&0x1000u64.to_le_bytes()); // p_align
+        assert_eq!(image.len(), headers_len);
+
+        image.extend_from_slice(body);
+        image
+    }
+
+    let vaddr = 0x0000_0000_0040_0000u64;
+    let image = elf64(vaddr, 0x5 /* PF_R | PF_X */, &[0x90; 16]);
+
+    let first = load_elf(&image);
+    assert_eq!(first.entry_point.as_u64(), vaddr);
+
+    // Every call builds a fresh address space, so each load gets its own PML4 frame.
+    let second = load_elf(&image);
+    assert_ne!(first.cr3, second.cr3);
+}
+
 #[test_case]
 fn test_print() {
     print!("")
@@ -162,6 +324,25 @@ fn test_box_heap_alloc() {
 
 
 
+#[test_case]
+fn test_fixed_size_block_reuse() {
+    use core::alloc::{GlobalAlloc, Layout};
+    use memory::allocator::fixed_size_block::ALLOCATOR;
+
+    // Smallest block class (8 bytes): freeing and re-allocating should hand back
+    // the same block from its free list instead of carving a fresh one.
+    let layout = Layout::from_size_align(8, 8).unwrap();
+    unsafe {
+        let first = ALLOCATOR.alloc(layout);
+        assert!(!first.is_null());
+        ALLOCATOR.dealloc(first, layout);
+
+        let second = ALLOCATOR.alloc(layout);
+        assert_eq!(first, second);
+        ALLOCATOR.dealloc(second, layout);
+    }
+}
+
 #[test_case]
 fn test_vec_heap_alloc() {
     use alloc::vec::Vec;